@@ -0,0 +1,38 @@
+mod data;
+
+pub use data::Data;
+use ruma::RoomId;
+
+use crate::{services, Result};
+
+pub struct Service {
+    pub db: &'static dyn Data,
+}
+
+impl Service {
+    /// Returns the `shortstatehash` of `room_id`'s current (most recently
+    /// resolved) state.
+    pub fn get_room_shortstatehash(&self, room_id: &RoomId) -> Result<Option<u64>> {
+        self.db.get_room_shortstatehash(room_id)
+    }
+
+    /// Persists `shortstatehash` as `room_id`'s new current resolved state.
+    ///
+    /// This is the path a room's state pointer moves forward through, e.g.
+    /// after a newly appended PDU is state-resolved, or after a room
+    /// upgrade/resync rewrites state. Visibility decisions already cached
+    /// under `shortstatehash` may predate this write and no longer describe
+    /// the room's current permissions, so invalidate them here rather than
+    /// relying on every caller of this function to remember to. Entries
+    /// cached under the previous pointer are left in place.
+    pub fn set_room_shortstatehash(&self, room_id: &RoomId, shortstatehash: u64) -> Result<()> {
+        self.db.set_room_shortstatehash(room_id, shortstatehash)?;
+
+        services()
+            .rooms
+            .state_accessor
+            .invalidate_visibility_cache_for_state(shortstatehash);
+
+        Ok(())
+    }
+}
@@ -9,13 +9,18 @@ use lru_cache::LruCache;
 use ruma::{
     events::{
         room::{
+            avatar::RoomAvatarEventContent,
+            canonical_alias::RoomCanonicalAliasEventContent,
             history_visibility::{HistoryVisibility, RoomHistoryVisibilityEventContent},
             member::{MembershipState, RoomMemberEventContent},
+            name::RoomNameEventContent,
         },
-        StateEventType,
+        EventContent, StateEventType,
     },
-    EventId, OwnedServerName, OwnedUserId, RoomId, ServerName, UserId,
+    EventId, OwnedMxcUri, OwnedRoomAliasId, OwnedServerName, OwnedUserId, RoomId, ServerName,
+    UserId,
 };
+use serde::de::DeserializeOwned;
 use tracing::error;
 
 use crate::{services, Error, PduEvent, Result};
@@ -23,6 +28,33 @@ use crate::{services, Error, PduEvent, Result};
 pub struct Service {
     pub db: &'static dyn Data,
     pub server_visibility_cache: Mutex<LruCache<(OwnedServerName, u64), bool>>,
+    pub user_visibility_cache: Mutex<LruCache<(OwnedUserId, u64), bool>>,
+    pub room_summary_cache: Mutex<LruCache<(u64, OwnedUserId), RoomMemberCounts>>,
+}
+
+/// Member counts and heroes for a room's state at a given `shortstatehash`,
+/// already filtered for one `for_user`, used to build a [`RoomSummary`]
+/// without re-walking the full state on every call. Cached per
+/// `(shortstatehash, for_user)` since the heroes exclude `for_user`.
+#[derive(Debug, Clone)]
+pub struct RoomMemberCounts {
+    pub joined_member_count: u64,
+    pub invited_member_count: u64,
+    pub heroes: Vec<OwnedUserId>,
+}
+
+/// A compact summary of a room's state, suitable for `/sync` responses and
+/// room-list UIs.
+#[derive(Debug, Clone)]
+pub struct RoomSummary {
+    pub joined_member_count: u64,
+    pub invited_member_count: u64,
+    /// Up to five other joined/invited members, excluding the user the
+    /// summary is being computed for, for clients to use as a name fallback.
+    pub heroes: Vec<OwnedUserId>,
+    pub room_name: Option<String>,
+    pub canonical_alias: Option<OwnedRoomAliasId>,
+    pub avatar_url: Option<OwnedMxcUri>,
 }
 
 impl Service {
@@ -61,18 +93,34 @@ impl Service {
         self.db.state_get(shortstatehash, event_type, state_key)
     }
 
+    /// Returns the deserialized content of a single state event, or `None`
+    /// if the state event is absent.
+    pub fn state_get_content<C>(
+        &self,
+        shortstatehash: u64,
+        event_type: &StateEventType,
+        state_key: &str,
+    ) -> Result<Option<C>>
+    where
+        C: EventContent + DeserializeOwned,
+    {
+        self.state_get(shortstatehash, event_type, state_key)?
+            .map(|pdu| {
+                serde_json::from_str(pdu.content.get())
+                    .map_err(|_| Error::bad_database("Invalid state event content in database."))
+            })
+            .transpose()
+    }
+
     /// Get membership for given user in state
     fn user_membership(&self, shortstatehash: u64, user_id: &UserId) -> Result<MembershipState> {
-        self.state_get(
-            shortstatehash,
-            &StateEventType::RoomMember,
-            user_id.as_str(),
-        )?
-        .map_or(Ok(MembershipState::Leave), |s| {
-            serde_json::from_str(s.content.get())
-                .map(|c: RoomMemberEventContent| c.membership)
-                .map_err(|_| Error::bad_database("Invalid room membership event in database."))
-        })
+        Ok(self
+            .state_get_content::<RoomMemberEventContent>(
+                shortstatehash,
+                &StateEventType::RoomMember,
+                user_id.as_str(),
+            )?
+            .map_or(MembershipState::Leave, |c| c.membership))
     }
 
     /// The user was a joined member at this state (potentially in the past)
@@ -114,14 +162,12 @@ impl Service {
         }
 
         let history_visibility = self
-            .state_get(shortstatehash, &StateEventType::RoomHistoryVisibility, "")?
-            .map_or(Ok(HistoryVisibility::Shared), |s| {
-                serde_json::from_str(s.content.get())
-                    .map(|c: RoomHistoryVisibilityEventContent| c.history_visibility)
-                    .map_err(|_| {
-                        Error::bad_database("Invalid history visibility event in database.")
-                    })
-            })?;
+            .state_get_content::<RoomHistoryVisibilityEventContent>(
+                shortstatehash,
+                &StateEventType::RoomHistoryVisibility,
+                "",
+            )?
+            .map_or(HistoryVisibility::Shared, |c| c.history_visibility);
 
         let mut current_server_members = services()
             .rooms
@@ -154,11 +200,392 @@ impl Service {
         Ok(visibility)
     }
 
+    /// Whether a user is allowed to see an event, based on the room's
+    /// history_visibility at that event's state and the user's membership.
+    ///
+    /// Not yet called from the `/sync`, `/messages`, or `/context` handlers
+    /// — wiring those request handlers to use this instead of
+    /// `server_can_see_event`-style federation checks is out of scope for
+    /// the change that introduced this function and is left for follow-up.
+    #[tracing::instrument(skip(self))]
+    pub fn user_can_see_event(
+        &self,
+        user_id: &UserId,
+        room_id: &RoomId,
+        event_id: &EventId,
+    ) -> Result<bool> {
+        let shortstatehash = match self.pdu_shortstatehash(event_id)? {
+            Some(shortstatehash) => shortstatehash,
+            None => return Ok(false),
+        };
+
+        if let Some(visibility) = self
+            .user_visibility_cache
+            .lock()
+            .unwrap()
+            .get_mut(&(user_id.to_owned(), shortstatehash))
+        {
+            return Ok(*visibility);
+        }
+
+        let history_visibility = self
+            .state_get_content::<RoomHistoryVisibilityEventContent>(
+                shortstatehash,
+                &StateEventType::RoomHistoryVisibility,
+                "",
+            )?
+            .map_or(HistoryVisibility::Shared, |c| c.history_visibility);
+
+        let visibility = match history_visibility {
+            HistoryVisibility::WorldReadable => true,
+            HistoryVisibility::Shared | HistoryVisibility::Invited | HistoryVisibility::Joined => {
+                let was_joined_at_event = self.user_was_joined(shortstatehash, user_id);
+                let was_invited_at_event = self.user_was_invited(shortstatehash, user_id);
+
+                // Skip the grandfathered-access lookup entirely under
+                // `Joined`, where it never applies (see
+                // `tiered_history_visibility_allows`).
+                let retroactively_allowed = if matches!(history_visibility, HistoryVisibility::Joined)
+                {
+                    false
+                } else {
+                    self.user_membership_began_at_or_before(room_id, user_id, shortstatehash)?
+                };
+
+                Self::tiered_history_visibility_allows(
+                    &history_visibility,
+                    was_joined_at_event,
+                    was_invited_at_event,
+                    retroactively_allowed,
+                )
+            }
+            _ => {
+                error!("Unknown history visibility {history_visibility}");
+                false
+            }
+        };
+
+        self.user_visibility_cache
+            .lock()
+            .unwrap()
+            .insert((user_id.to_owned(), shortstatehash), visibility);
+
+        Ok(visibility)
+    }
+
+    /// Pure decision for the `Shared`/`Invited`/`Joined` history-visibility
+    /// tiers, given the user's membership at the event's state and whether
+    /// they're eligible for retroactive (grandfathered) access to state at
+    /// or after the start of their current membership. Split out from
+    /// `user_can_see_event` so the tier logic is unit-testable without a
+    /// database.
+    fn tiered_history_visibility_allows(
+        history_visibility: &HistoryVisibility,
+        was_joined_at_event: bool,
+        was_invited_at_event: bool,
+        retroactively_allowed: bool,
+    ) -> bool {
+        if was_joined_at_event {
+            return true;
+        }
+
+        if matches!(
+            history_visibility,
+            HistoryVisibility::Invited | HistoryVisibility::Shared
+        ) && was_invited_at_event
+        {
+            return true;
+        }
+
+        if matches!(history_visibility, HistoryVisibility::Joined) {
+            // `Joined` is strictly "visible only while actually joined at
+            // that time" -- no grandfathered read-back, unlike
+            // `Shared`/`Invited` below.
+            return false;
+        }
+
+        // Let a currently joined/invited user read back to the point where
+        // their membership began, i.e. see events at or after their
+        // join/invite. A user who has since left or been banned gets
+        // no such grandfathered access (`retroactively_allowed` is always
+        // `false` for them; see `membership_permits_retroactive_access`).
+        retroactively_allowed
+    }
+
+    /// Whether `shortstatehash` is at or after the state where the user's
+    /// *current* membership in `room_id` began, provided that membership is
+    /// actually `Join` or `Invite`. A user whose current membership is
+    /// `Leave` or `Ban` must not be grandfathered into reading events sent
+    /// after they left/were banned.
+    fn user_membership_began_at_or_before(
+        &self,
+        room_id: &RoomId,
+        user_id: &UserId,
+        shortstatehash: u64,
+    ) -> Result<bool> {
+        let member_event =
+            match self.room_state_get(room_id, &StateEventType::RoomMember, user_id.as_str())? {
+                Some(member_event) => member_event,
+                None => return Ok(false),
+            };
+
+        let content: RoomMemberEventContent = match serde_json::from_str(member_event.content.get())
+        {
+            Ok(content) => content,
+            Err(_) => return Ok(false),
+        };
+
+        if !Self::membership_permits_retroactive_access(&content.membership) {
+            return Ok(false);
+        }
+
+        match self.pdu_shortstatehash(&member_event.event_id)? {
+            Some(membership_shortstatehash) => Ok(shortstatehash >= membership_shortstatehash),
+            None => Ok(false),
+        }
+    }
+
+    /// Whether `membership` is a state that's eligible for grandfathered
+    /// read-back to the point where it began. Only a currently joined or invited
+    /// member gets this; a user who left or was banned must not keep
+    /// reading events sent after they left.
+    fn membership_permits_retroactive_access(membership: &MembershipState) -> bool {
+        matches!(membership, MembershipState::Join | MembershipState::Invite)
+    }
+
     /// Returns the state hash for this pdu.
     pub fn pdu_shortstatehash(&self, event_id: &EventId) -> Result<Option<u64>> {
         self.db.pdu_shortstatehash(event_id)
     }
 
+    /// Invalidate cached visibility decisions for `room_id`'s current
+    /// resolved state.
+    ///
+    /// Call this whenever a room's state is rewritten (e.g. after a state
+    /// resolution or room upgrade), so federation peers and users aren't
+    /// served stale allow/deny decisions for the new state.
+    pub fn invalidate_visibility_cache(&self, room_id: &RoomId) -> Result<()> {
+        if let Some(shortstatehash) = services().rooms.state.get_room_shortstatehash(room_id)? {
+            self.invalidate_visibility_cache_for_state(shortstatehash);
+        }
+
+        Ok(())
+    }
+
+    /// Invalidate cached visibility decisions keyed on `shortstatehash`.
+    pub fn invalidate_visibility_cache_for_state(&self, shortstatehash: u64) {
+        let mut server_cache = self.server_visibility_cache.lock().unwrap();
+        let stale_servers: Vec<_> = server_cache
+            .iter()
+            .filter(|((_, hash), _)| *hash == shortstatehash)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in stale_servers {
+            server_cache.remove(&key);
+        }
+        drop(server_cache);
+
+        let mut user_cache = self.user_visibility_cache.lock().unwrap();
+        let stale_users: Vec<_> = user_cache
+            .iter()
+            .filter(|((_, hash), _)| *hash == shortstatehash)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in stale_users {
+            user_cache.remove(&key);
+        }
+    }
+
+    /// Builds a compact summary of `room_id`'s current state: member counts,
+    /// up to five heroes (other joined/invited members, for name fallback),
+    /// and the resolved name, canonical alias and avatar.
+    ///
+    /// Not yet called from the `/sync` room-list path this was added for —
+    /// wiring the sync handler to use this instead of building summaries
+    /// ad hoc is left for follow-up.
+    #[tracing::instrument(skip(self))]
+    pub async fn room_summary(&self, room_id: &RoomId, for_user: &UserId) -> Result<RoomSummary> {
+        let shortstatehash = services()
+            .rooms
+            .state
+            .get_room_shortstatehash(room_id)?
+            .ok_or_else(|| Error::bad_database("Room has no state."))?;
+
+        let counts = self.room_member_counts(shortstatehash, for_user).await?;
+
+        let room_name = self
+            .room_state_get_content::<RoomNameEventContent>(room_id, &StateEventType::RoomName, "")?
+            .map(|c| c.name);
+
+        let canonical_alias = self
+            .room_state_get_content::<RoomCanonicalAliasEventContent>(
+                room_id,
+                &StateEventType::RoomCanonicalAlias,
+                "",
+            )?
+            .and_then(|c| c.alias);
+
+        let avatar_url = self
+            .room_state_get_content::<RoomAvatarEventContent>(
+                room_id,
+                &StateEventType::RoomAvatar,
+                "",
+            )?
+            .map(|c| c.url);
+
+        Ok(RoomSummary {
+            joined_member_count: counts.joined_member_count,
+            invited_member_count: counts.invited_member_count,
+            heroes: counts.heroes,
+            room_name,
+            canonical_alias,
+            avatar_url,
+        })
+    }
+
+    /// Returns the member counts and heroes for `shortstatehash`, excluding
+    /// `for_user` from the heroes, computing and caching them on first
+    /// access.
+    async fn room_member_counts(
+        &self,
+        shortstatehash: u64,
+        for_user: &UserId,
+    ) -> Result<RoomMemberCounts> {
+        let cache_key = (shortstatehash, for_user.to_owned());
+
+        if let Some(counts) = self
+            .room_summary_cache
+            .lock()
+            .unwrap()
+            .get_mut(&cache_key)
+        {
+            return Ok(counts.clone());
+        }
+
+        let state = self.state_full(shortstatehash).await?;
+
+        let mut joined_member_count = 0_u64;
+        let mut invited_member_count = 0_u64;
+        // (user_id, origin_server_ts) candidates; `select_heroes` sorts
+        // these so heroes are a deterministic subset rather than however
+        // the backing HashMap happened to iterate this time.
+        let mut hero_candidates = Vec::new();
+
+        for ((event_type, state_key), pdu) in &state {
+            if *event_type != StateEventType::RoomMember {
+                continue;
+            }
+
+            let user_id = match UserId::parse(state_key.as_str()) {
+                Ok(user_id) => user_id,
+                Err(_) => continue,
+            };
+
+            let content: RoomMemberEventContent = match serde_json::from_str(pdu.content.get()) {
+                Ok(content) => content,
+                Err(_) => continue,
+            };
+
+            match content.membership {
+                MembershipState::Join => {
+                    joined_member_count += 1;
+                    hero_candidates.push((user_id.to_owned(), pdu.origin_server_ts));
+                }
+                MembershipState::Invite => {
+                    invited_member_count += 1;
+                    hero_candidates.push((user_id.to_owned(), pdu.origin_server_ts));
+                }
+                _ => {}
+            }
+        }
+
+        let heroes = Self::select_heroes(hero_candidates, for_user);
+
+        let counts = RoomMemberCounts {
+            joined_member_count,
+            invited_member_count,
+            heroes,
+        };
+
+        self.room_summary_cache
+            .lock()
+            .unwrap()
+            .insert(cache_key, counts.clone());
+
+        Ok(counts)
+    }
+
+    /// Picks up to five heroes from `candidates`, sorted by `ts` (tie-broken
+    /// by user ID) and excluding `for_user`, so the same room state always
+    /// yields the same heroes regardless of how `candidates` was ordered
+    /// going in. Split out from `room_member_counts` so the ordering and
+    /// exclusion logic is unit-testable without a database.
+    fn select_heroes<T: Ord>(
+        mut candidates: Vec<(OwnedUserId, T)>,
+        for_user: &UserId,
+    ) -> Vec<OwnedUserId> {
+        candidates.sort_by(|(a_user, a_ts), (b_user, b_ts)| {
+            a_ts.cmp(b_ts).then_with(|| a_user.cmp(b_user))
+        });
+
+        candidates
+            .into_iter()
+            .map(|(user_id, _)| user_id)
+            .filter(|user_id| user_id != for_user)
+            .take(5)
+            .collect()
+    }
+
+    /// Incrementally updates a cached room summary's member counts and
+    /// heroes when a single membership event moves the room from
+    /// `old_shortstatehash` to `new_shortstatehash`, without re-walking
+    /// `state_full`. No-op if the old state wasn't cached.
+    pub fn update_room_summary_membership(
+        &self,
+        old_shortstatehash: u64,
+        new_shortstatehash: u64,
+        for_user: &UserId,
+        user_id: &UserId,
+        old_membership: Option<&MembershipState>,
+        new_membership: &MembershipState,
+    ) {
+        let mut cache = self.room_summary_cache.lock().unwrap();
+        let old_cache_key = (old_shortstatehash, for_user.to_owned());
+        let mut counts = match cache.get_mut(&old_cache_key) {
+            Some(counts) => counts.clone(),
+            None => return,
+        };
+
+        if let Some(old_membership) = old_membership {
+            match old_membership {
+                MembershipState::Join => {
+                    counts.joined_member_count = counts.joined_member_count.saturating_sub(1);
+                }
+                MembershipState::Invite => {
+                    counts.invited_member_count = counts.invited_member_count.saturating_sub(1);
+                }
+                _ => {}
+            }
+            counts.heroes.retain(|hero| hero != user_id);
+        }
+
+        match new_membership {
+            MembershipState::Join => counts.joined_member_count += 1,
+            MembershipState::Invite => counts.invited_member_count += 1,
+            _ => {}
+        }
+
+        if matches!(new_membership, MembershipState::Join | MembershipState::Invite)
+            && user_id != for_user
+            && counts.heroes.len() < 5
+            && !counts.heroes.iter().any(|hero| hero == user_id)
+        {
+            counts.heroes.push(user_id.to_owned());
+        }
+
+        cache.insert((new_shortstatehash, for_user.to_owned()), counts);
+    }
+
     /// Returns the full room state.
     #[tracing::instrument(skip(self))]
     pub async fn room_state_full(
@@ -189,4 +616,297 @@ impl Service {
     ) -> Result<Option<Arc<PduEvent>>> {
         self.db.room_state_get(room_id, event_type, state_key)
     }
+
+    /// Fetches only the `m.room.member` PDUs for `senders`, by state key,
+    /// without materializing the full `StateMap` via `state_full`. Lets the
+    /// sync layer honor `lazy_load_members` without an O(room-size) walk.
+    ///
+    /// Not yet called from the sync lazy-loading filter — wiring that up is
+    /// left for follow-up, together with [`Self::redundant_member_event`].
+    pub fn state_members_for_senders(
+        &self,
+        shortstatehash: u64,
+        senders: &[OwnedUserId],
+    ) -> Result<HashMap<OwnedUserId, Arc<PduEvent>>> {
+        let mut members = HashMap::new();
+
+        for sender in senders {
+            if let Some(pdu) =
+                self.state_get(shortstatehash, &StateEventType::RoomMember, sender.as_str())?
+            {
+                members.insert(sender.clone(), pdu);
+            }
+        }
+
+        Ok(members)
+    }
+
+    /// Whether `user_id`'s member state is unchanged between
+    /// `previously_sent_state_hash` and `shortstatehash`, so a lazy-loading
+    /// sync can skip re-sending it.
+    pub fn redundant_member_event(
+        &self,
+        shortstatehash: u64,
+        user_id: &UserId,
+        previously_sent_state_hash: u64,
+    ) -> Result<bool> {
+        let current =
+            self.state_get_id(shortstatehash, &StateEventType::RoomMember, user_id.as_str())?;
+        let previous = self.state_get_id(
+            previously_sent_state_hash,
+            &StateEventType::RoomMember,
+            user_id.as_str(),
+        )?;
+
+        Ok(current == previous)
+    }
+
+    /// Returns the deserialized content of a single state event, or `None`
+    /// if the state event is absent.
+    pub fn room_state_get_content<C>(
+        &self,
+        room_id: &RoomId,
+        event_type: &StateEventType,
+        state_key: &str,
+    ) -> Result<Option<C>>
+    where
+        C: EventContent + DeserializeOwned,
+    {
+        self.room_state_get(room_id, event_type, state_key)?
+            .map(|pdu| {
+                serde_json::from_str(pdu.content.get())
+                    .map_err(|_| Error::bad_database("Invalid state event content in database."))
+            })
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn joined_tier_allows_when_joined_at_event() {
+        assert!(Service::tiered_history_visibility_allows(
+            &HistoryVisibility::Joined,
+            true,
+            false,
+            false,
+        ));
+    }
+
+    #[test]
+    fn joined_tier_ignores_invited_at_event_and_retroactive_access() {
+        // `Joined` is strictly "visible only while actually joined at that
+        // time" -- being invited at the event, or being eligible for
+        // grandfathered read-back, must not grant access.
+        assert!(!Service::tiered_history_visibility_allows(
+            &HistoryVisibility::Joined,
+            false,
+            true,
+            true,
+        ));
+    }
+
+    #[test]
+    fn shared_tier_allows_invited_at_event() {
+        assert!(Service::tiered_history_visibility_allows(
+            &HistoryVisibility::Shared,
+            false,
+            true,
+            false,
+        ));
+    }
+
+    #[test]
+    fn shared_tier_grants_retroactive_access_to_currently_joined_user() {
+        assert!(Service::tiered_history_visibility_allows(
+            &HistoryVisibility::Shared,
+            false,
+            false,
+            true,
+        ));
+    }
+
+    #[test]
+    fn shared_tier_denies_without_any_membership_or_retroactive_access() {
+        assert!(!Service::tiered_history_visibility_allows(
+            &HistoryVisibility::Shared,
+            false,
+            false,
+            false,
+        ));
+    }
+
+    #[test]
+    fn invited_tier_allows_invited_at_event() {
+        assert!(Service::tiered_history_visibility_allows(
+            &HistoryVisibility::Invited,
+            false,
+            true,
+            false,
+        ));
+    }
+
+    #[test]
+    fn invited_tier_grants_retroactive_access_when_not_invited_at_event() {
+        assert!(Service::tiered_history_visibility_allows(
+            &HistoryVisibility::Invited,
+            false,
+            false,
+            true,
+        ));
+    }
+
+    #[test]
+    fn invited_tier_denies_without_any_membership_or_retroactive_access() {
+        assert!(!Service::tiered_history_visibility_allows(
+            &HistoryVisibility::Invited,
+            false,
+            false,
+            false,
+        ));
+    }
+
+    #[test]
+    fn retroactive_access_permitted_only_for_join_and_invite() {
+        assert!(Service::membership_permits_retroactive_access(
+            &MembershipState::Join
+        ));
+        assert!(Service::membership_permits_retroactive_access(
+            &MembershipState::Invite
+        ));
+        assert!(!Service::membership_permits_retroactive_access(
+            &MembershipState::Leave
+        ));
+        assert!(!Service::membership_permits_retroactive_access(
+            &MembershipState::Ban
+        ));
+    }
+
+    #[test]
+    fn banned_then_rejoined_user_can_read_back_under_shared_visibility() {
+        // Current membership is `Join` after a ban + rejoin; they weren't
+        // joined/invited *at* the older event's state, but now that
+        // they're rejoined, `Shared` visibility should grant retroactive
+        // access again.
+        let retroactively_allowed =
+            Service::membership_permits_retroactive_access(&MembershipState::Join);
+        assert!(Service::tiered_history_visibility_allows(
+            &HistoryVisibility::Shared,
+            false,
+            false,
+            retroactively_allowed,
+        ));
+    }
+
+    #[test]
+    fn banned_user_cannot_read_back_even_under_shared_visibility() {
+        let retroactively_allowed =
+            Service::membership_permits_retroactive_access(&MembershipState::Ban);
+        assert!(!Service::tiered_history_visibility_allows(
+            &HistoryVisibility::Shared,
+            false,
+            false,
+            retroactively_allowed,
+        ));
+    }
+
+    #[test]
+    fn left_then_rejoined_user_can_read_back_under_invited_visibility() {
+        let retroactively_allowed =
+            Service::membership_permits_retroactive_access(&MembershipState::Join);
+        assert!(Service::tiered_history_visibility_allows(
+            &HistoryVisibility::Invited,
+            false,
+            false,
+            retroactively_allowed,
+        ));
+    }
+
+    #[test]
+    fn left_user_cannot_read_back_even_under_invited_visibility() {
+        let retroactively_allowed =
+            Service::membership_permits_retroactive_access(&MembershipState::Leave);
+        assert!(!Service::tiered_history_visibility_allows(
+            &HistoryVisibility::Invited,
+            false,
+            false,
+            retroactively_allowed,
+        ));
+    }
+
+    fn user_id(s: &str) -> OwnedUserId {
+        UserId::parse(s).unwrap().to_owned()
+    }
+
+    #[test]
+    fn select_heroes_orders_by_timestamp_then_user_id() {
+        let alice = user_id("@alice:example.com");
+        let bob = user_id("@bob:example.com");
+        let carol = user_id("@carol:example.com");
+
+        // bob is listed first but has the latest ts (30); alice/carol tie on
+        // ts 10, broken by user ID, so the expected order is alice, carol, bob.
+        let candidates = vec![(bob.clone(), 30), (alice.clone(), 10), (carol.clone(), 10)];
+
+        let heroes = Service::select_heroes(candidates, &user_id("@nobody:example.com"));
+
+        assert_eq!(heroes, vec![alice, carol, bob]);
+    }
+
+    #[test]
+    fn select_heroes_excludes_for_user() {
+        let alice = user_id("@alice:example.com");
+        let bob = user_id("@bob:example.com");
+
+        let candidates = vec![(alice.clone(), 1), (bob.clone(), 2)];
+
+        let heroes = Service::select_heroes(candidates, &alice);
+
+        assert_eq!(heroes, vec![bob]);
+    }
+
+    #[test]
+    fn select_heroes_truncates_to_five() {
+        let candidates: Vec<_> = (0..10)
+            .map(|i| (user_id(&format!("@user{i}:example.com")), i))
+            .collect();
+
+        let heroes = Service::select_heroes(candidates, &user_id("@nobody:example.com"));
+
+        assert_eq!(heroes.len(), 5);
+    }
+
+    #[test]
+    fn room_summary_cache_is_keyed_per_user_not_just_shortstatehash() {
+        // Regression test: the cache used to be keyed by `shortstatehash`
+        // alone, so one user's computed heroes (which exclude that user)
+        // could leak into another user's `room_summary` call for the same
+        // state.
+        let alice = user_id("@alice:example.com");
+        let bob = user_id("@bob:example.com");
+
+        let mut cache: LruCache<(u64, OwnedUserId), RoomMemberCounts> = LruCache::new(10);
+
+        let counts_excluding_alice = RoomMemberCounts {
+            joined_member_count: 2,
+            invited_member_count: 0,
+            heroes: vec![bob.clone()],
+        };
+        let counts_excluding_bob = RoomMemberCounts {
+            joined_member_count: 2,
+            invited_member_count: 0,
+            heroes: vec![alice.clone()],
+        };
+
+        cache.insert((1, alice.clone()), counts_excluding_alice);
+        cache.insert((1, bob.clone()), counts_excluding_bob);
+
+        assert_eq!(
+            cache.get_mut(&(1, alice.clone())).unwrap().heroes,
+            vec![bob.clone()]
+        );
+        assert_eq!(cache.get_mut(&(1, bob)).unwrap().heroes, vec![alice]);
+    }
 }